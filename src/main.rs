@@ -1,33 +1,499 @@
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
 use termios::*;
 
 const VERSION: &str = "0.0.1";
+const TAB_STOP: usize = 8;
+const QUIT_TIMES: u8 = 3;
+// Files at or above this size are opened through a windowed `CachingFileView`
+// instead of being read into memory whole.
+const LARGE_FILE_THRESHOLD: u64 = 8 * 1024 * 1024;
+// Size of the byte window held resident by the caching view.
+const CACHE_SIZE: usize = 1024 * 1024;
 
-const CLEAR_SCREEN: &str = "\x1b[2J";
 const CLEAR_LINE: &str = "\x1b[K";
 const HIDE_CURSOR: &str = "\x1b[?25l";
 const SHOW_CURSOR: &str = "\x1b[?25h";
 
+// Editing mode in the vi-style dispatcher.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Normal,
+    Insert,
+    Command,
+}
+
+impl Mode {
+    fn label(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Command => "COMMAND",
+        }
+    }
+}
+
 struct Erow {
     size: u16,
     chars: String,
+    render: String,
+}
+
+// Compute the on-screen rendering of a row: tabs expand to the next `TAB_STOP`
+// column boundary and other control characters show as caret notation (`^A`).
+fn render_chars(chars: &str) -> String {
+    let mut render = String::new();
+    let mut col = 0usize;
+    for c in chars.chars() {
+        if c == '\t' {
+            render.push(' ');
+            col += 1;
+            while col % TAB_STOP != 0 {
+                render.push(' ');
+                col += 1;
+            }
+        } else if (c as u32) < 32 {
+            render.push('^');
+            render.push(((c as u8) + b'@') as char);
+            col += 2;
+        } else {
+            render.push(c);
+            col += 1;
+        }
+    }
+    render
+}
+
+// Rendered column width of a single decoded character.
+fn char_render_width(c: char, rx: u16) -> u16 {
+    if c == '\t' {
+        TAB_STOP as u16 - (rx % TAB_STOP as u16)
+    } else if (c as u32) < 32 {
+        2
+    } else {
+        1
+    }
+}
+
+// Convert a `chars` character index into its rendered column.
+fn cx_to_rx(row: &Erow, cx: u16) -> u16 {
+    let mut rx = 0u16;
+    for c in row.chars.chars().take(cx as usize) {
+        rx += char_render_width(c, rx);
+    }
+    rx
+}
+
+// Convert a rendered column back into a `chars` character index.
+fn rx_to_cx(row: &Erow, rx: u16) -> u16 {
+    let mut cur_rx = 0u16;
+    for (cx, c) in row.chars.chars().enumerate() {
+        cur_rx += char_render_width(c, cur_rx);
+        if cur_rx > rx {
+            return cx as u16;
+        }
+    }
+    row.chars.chars().count() as u16
+}
+
+// Which immutable backing buffer a piece points into.
+#[derive(Clone, Copy)]
+enum Buffer {
+    Original,
+    Add,
+}
+
+// A contiguous run of bytes in one of the backing buffers.
+#[derive(Clone, Copy)]
+struct Piece {
+    buffer: Buffer,
+    start: usize,
+    len: usize,
+}
+
+// The document as an ordered list of pieces over two immutable buffers: the
+// `original` file bytes and an append-only `add` buffer. Edits never rewrite
+// bytes already in the buffers; they append to `add` and change the `pieces`
+// list, which keeps insert/delete cheap.
+struct PieceTable {
+    original: String,
+    add: String,
+    pieces: Vec<Piece>,
+}
+
+impl PieceTable {
+    fn new(original: String) -> PieceTable {
+        let mut pieces = Vec::new();
+        if !original.is_empty() {
+            pieces.push(Piece {
+                buffer: Buffer::Original,
+                start: 0,
+                len: original.len(),
+            });
+        }
+        PieceTable {
+            original,
+            add: String::new(),
+            pieces,
+        }
+    }
+
+    fn total_len(&self) -> usize {
+        self.pieces.iter().map(|p| p.len).sum()
+    }
+
+    fn buffer(&self, buffer: Buffer) -> &str {
+        match buffer {
+            Buffer::Original => &self.original,
+            Buffer::Add => &self.add,
+        }
+    }
+
+    // Materialize the whole document. Rendering walks this to rebuild `Erow`s.
+    fn text(&self) -> String {
+        let mut out = String::with_capacity(self.total_len());
+        for p in &self.pieces {
+            out.push_str(&self.buffer(p.buffer)[p.start..p.start + p.len]);
+        }
+        out
+    }
+
+    // Insert `text` at a logical byte offset, splitting the straddled piece into
+    // up-to-three pieces (left remainder, new piece pointing into `add`, right
+    // remainder).
+    fn insert(&mut self, offset: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let start = self.add.len();
+        self.add.push_str(text);
+        let new_piece = Piece {
+            buffer: Buffer::Add,
+            start,
+            len: text.len(),
+        };
+
+        let mut acc = 0;
+        for i in 0..self.pieces.len() {
+            let piece_len = self.pieces[i].len;
+            if offset <= acc + piece_len {
+                let split = offset - acc;
+                if split == 0 {
+                    self.pieces.insert(i, new_piece);
+                } else if split == piece_len {
+                    self.pieces.insert(i + 1, new_piece);
+                } else {
+                    let p = self.pieces[i];
+                    let left = Piece {
+                        buffer: p.buffer,
+                        start: p.start,
+                        len: split,
+                    };
+                    let right = Piece {
+                        buffer: p.buffer,
+                        start: p.start + split,
+                        len: p.len - split,
+                    };
+                    self.pieces.splice(i..=i, [left, new_piece, right]);
+                }
+                return;
+            }
+            acc += piece_len;
+        }
+        // Offset is at (or past) the end of the document.
+        self.pieces.push(new_piece);
+    }
+
+    // Delete `len` bytes starting at a logical byte offset, trimming or dropping
+    // the pieces the range spans.
+    fn delete(&mut self, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let end = offset + len;
+        let mut acc = 0;
+        let mut kept = Vec::with_capacity(self.pieces.len());
+        for p in &self.pieces {
+            let p_start = acc;
+            let p_end = acc + p.len;
+            acc = p_end;
+            if p_end <= offset || p_start >= end {
+                kept.push(*p);
+                continue;
+            }
+            if p_start < offset {
+                kept.push(Piece {
+                    buffer: p.buffer,
+                    start: p.start,
+                    len: offset - p_start,
+                });
+            }
+            if p_end > end {
+                let skip = end - p_start;
+                kept.push(Piece {
+                    buffer: p.buffer,
+                    start: p.start + skip,
+                    len: p_end - end,
+                });
+            }
+        }
+        self.pieces = kept;
+    }
+}
+
+// A windowed view over a file too large to hold in memory. Only a fixed-size
+// byte window is kept resident; a line-offset index is grown lazily as the user
+// scrolls so `row_offset` can be mapped to a file position on demand.
+struct CachingFileView {
+    file: File,
+    file_len: u64,
+    cache_start: u64,
+    cache: Vec<u8>,
+    // Byte offset of each known line start, `line_offsets[0]` == 0. Grows as the
+    // user scrolls further into the file.
+    line_offsets: Vec<u64>,
+    eof_reached: bool,
+}
+
+impl CachingFileView {
+    fn new(mut file: File) -> io::Result<CachingFileView> {
+        let file_len = file.seek(SeekFrom::End(0))?;
+        Ok(CachingFileView {
+            file,
+            file_len,
+            cache_start: 0,
+            cache: Vec::new(),
+            line_offsets: vec![0],
+            eof_reached: file_len == 0,
+        })
+    }
+
+    // Ensure the resident window contains `offset`, refilling so that `offset`
+    // sits roughly centered in the buffer.
+    fn ensure_cached(&mut self, offset: u64) {
+        let end = self.cache_start + self.cache.len() as u64;
+        if !self.cache.is_empty() && offset >= self.cache_start && offset < end {
+            return;
+        }
+        let half = (CACHE_SIZE / 2) as u64;
+        let start = offset.saturating_sub(half).min(self.file_len);
+        if self.file.seek(SeekFrom::Start(start)).is_err() {
+            return;
+        }
+        let mut buf = vec![0u8; CACHE_SIZE];
+        let n = self.file.read(&mut buf).unwrap_or(0);
+        buf.truncate(n);
+        self.cache = buf;
+        self.cache_start = start;
+    }
+
+    // Discover line starts up to (and including) `line`, scanning the cached
+    // window for newlines and refilling forward as needed.
+    fn ensure_index_through(&mut self, line: usize) {
+        let mut scan_from = *self.line_offsets.last().unwrap();
+        while !self.eof_reached && self.line_offsets.len() <= line + 1 {
+            if scan_from >= self.file_len {
+                self.eof_reached = true;
+                break;
+            }
+            self.ensure_cached(scan_from);
+            let rel = (scan_from - self.cache_start) as usize;
+            if rel >= self.cache.len() {
+                self.eof_reached = true;
+                break;
+            }
+            match self.cache[rel..].iter().position(|&b| b == b'\n') {
+                Some(pos) => {
+                    scan_from += pos as u64 + 1;
+                    self.line_offsets.push(scan_from);
+                }
+                None => {
+                    let cache_end = self.cache_start + self.cache.len() as u64;
+                    if cache_end >= self.file_len {
+                        self.eof_reached = true;
+                        break;
+                    }
+                    // Newline lies past the window; slide forward and retry.
+                    scan_from = cache_end;
+                }
+            }
+        }
+    }
+
+    // Number of whole lines discovered so far.
+    fn known_lines(&self) -> usize {
+        let starts = self.line_offsets.len();
+        if self.eof_reached {
+            // The final entry is a real line unless it sits exactly at EOF.
+            if self.line_offsets.last() == Some(&self.file_len) {
+                starts.saturating_sub(1)
+            } else {
+                starts
+            }
+        } else {
+            starts.saturating_sub(1)
+        }
+    }
+
+    // Read `[start, end)` from the file, reusing the cache when it covers the
+    // range and seeking directly otherwise.
+    fn read_range(&mut self, start: u64, end: u64) -> Vec<u8> {
+        if end <= start {
+            return Vec::new();
+        }
+        self.ensure_cached(start);
+        let cache_end = self.cache_start + self.cache.len() as u64;
+        if start >= self.cache_start && end <= cache_end {
+            let a = (start - self.cache_start) as usize;
+            let b = (end - self.cache_start) as usize;
+            return self.cache[a..b].to_vec();
+        }
+        let mut buf = vec![0u8; (end - start) as usize];
+        if self.file.seek(SeekFrom::Start(start)).is_ok() {
+            let _ = self.file.read_exact(&mut buf);
+        }
+        buf
+    }
+
+    // Build `Erow`s for the visible line range `[first, last)`. Returns the rows
+    // together with the absolute line of the first row and the number of lines
+    // known so far.
+    fn rows_for_range(&mut self, first: u16, last: u16) -> (Vec<Erow>, u16, u16) {
+        self.ensure_index_through(last as usize);
+        let line_count = self.known_lines();
+        let base = (first as usize).min(line_count) as u16;
+        let upper = (last as usize).min(line_count);
+
+        let mut rows = Vec::new();
+        for line in (base as usize)..upper {
+            let start = self.line_offsets[line];
+            let end = self
+                .line_offsets
+                .get(line + 1)
+                .copied()
+                .unwrap_or(self.file_len);
+            // Drop the trailing newline that terminates the line.
+            let content_end = if end > start {
+                let last_byte = self.read_range(end - 1, end);
+                if last_byte.first() == Some(&b'\n') {
+                    end - 1
+                } else {
+                    end
+                }
+            } else {
+                end
+            };
+            let bytes = self.read_range(start, content_end);
+            let chars = String::from_utf8_lossy(&bytes).into_owned();
+            rows.push(Erow {
+                size: chars.len() as u16,
+                render: render_chars(&chars),
+                chars,
+            });
+        }
+        (rows, base, line_count as u16)
+    }
 }
 
 struct EditorConfig {
-    orig_termios: Termios,
     screen_rows: u16,
     screen_cols: u16,
     cursor_x: u16,
     cursor_y: u16,
+    render_x: u16,
     num_rows: u16,
     rows: Vec<Erow>,
     row_offset: u16,
     col_offset: u16,
+    piece_table: PieceTable,
+    dirty: u64,
+    status_message: String,
+    status_message_time: Instant,
+    filename: Option<PathBuf>,
+    quit_times: u8,
+    find_last_match: i32,
+    find_direction: i32,
+    find_hl_row: Option<usize>,
+    find_hl_start: usize,
+    find_hl_len: usize,
+    mode: Mode,
+    should_quit: bool,
+    file_view: Option<CachingFileView>,
+    // Absolute line number of `rows[0]`; 0 in the in-memory mode, tracks the
+    // window start in the caching mode.
+    row_base: u16,
+    // Whether the loaded file ended with a newline, so saving can preserve that
+    // state rather than unconditionally adding one.
+    trailing_newline: bool,
+}
+
+// Restores the original terminal attributes when dropped, so raw mode is left
+// even if the program panics.
+struct RawGuard {
+    orig_termios: Termios,
+}
+
+impl RawGuard {
+    fn new() -> RawGuard {
+        RawGuard {
+            orig_termios: setup_terminal(),
+        }
+    }
+}
+
+impl Drop for RawGuard {
+    fn drop(&mut self) {
+        disable_raw_mode(&self.orig_termios);
+    }
+}
+
+// Switches into the terminal's alternate screen on construction and back to the
+// main screen on drop, preserving the user's shell scrollback across runs.
+struct ScreenGuard;
+
+impl ScreenGuard {
+    fn new() -> ScreenGuard {
+        print!("\x1b[?1049h");
+        let _ = io::stdout().flush();
+        ScreenGuard
+    }
+}
+
+impl Drop for ScreenGuard {
+    fn drop(&mut self) {
+        print!("\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+// Restore the terminal from a panic hook. `read_key`/`read_input` run on a
+// spawned thread and `die()` by panicking; that unwind never reaches `main`'s
+// guards, so without this hook the alternate screen and raw mode would leak.
+fn install_panic_hook(orig_termios: Termios) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        print!("\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = termios::tcsetattr(0, TCSAFLUSH, &orig_termios);
+        default_hook(info);
+    }));
+}
+
+// Input events delivered to the main loop over a channel, decoupling rendering
+// from the blocking stdin read.
+enum Event {
+    Key(u32),
+    Resize(u16, u16),
 }
 
 #[repr(u32)]
@@ -62,6 +528,10 @@ impl TryFrom<u32> for EditorKey {
                 x if x == EditorKey::Right as u32 => Ok(EditorKey::Right),
                 x if x == EditorKey::Up as u32 => Ok(EditorKey::Up),
                 x if x == EditorKey::Down as u32 => Ok(EditorKey::Down),
+                x if x == EditorKey::PageUp as u32 => Ok(EditorKey::PageUp),
+                x if x == EditorKey::PageDown as u32 => Ok(EditorKey::PageDown),
+                x if x == EditorKey::Home as u32 => Ok(EditorKey::Home),
+                x if x == EditorKey::End as u32 => Ok(EditorKey::End),
                 _ => Err(format!("Unknown key: {}", key)),
             }
         }
@@ -134,9 +604,8 @@ fn is_cntrl(key: u32) -> bool {
 }
 
 fn die(e: &str, err: &Option<Box<dyn Error>>) {
-    // disable_raw_mode(&Termios::from_fd(0).unwrap());
-    clear_and_reset_cursor(None);
-
+    // On the main thread, unwinding drops `RawGuard`/`ScreenGuard`; on the input
+    // thread the panic hook restores the terminal instead.
     if let Some(err) = err.as_ref() {
         panic!("{}: {}", e, err);
     } else {
@@ -197,8 +666,27 @@ fn get_window_size() -> (u16, u16) {
 // IO
 fn editor_open(editor: &mut EditorConfig, path: &str) {
     let path = PathBuf::from(path);
-    let file_result = fs::read_to_string(path);
-    let file_content = match file_result {
+    editor.filename = Some(path.clone());
+
+    // Large files are opened through the windowed caching view rather than being
+    // read into memory whole.
+    if let Ok(meta) = fs::metadata(&path) {
+        if meta.len() >= LARGE_FILE_THRESHOLD {
+            match File::open(&path).and_then(CachingFileView::new) {
+                Ok(view) => {
+                    editor.file_view = Some(view);
+                    file_view_materialize(editor);
+                    return;
+                }
+                Err(e) => die(
+                    format!("Error when trying to load file: {}", e).as_str(),
+                    &Some(Box::new(e)),
+                ),
+            }
+        }
+    }
+
+    let file_content = match fs::read_to_string(&path) {
         Ok(content) => content,
         Err(e) => {
             die(
@@ -208,14 +696,333 @@ fn editor_open(editor: &mut EditorConfig, path: &str) {
             panic!("Shouldn't get here");
         }
     };
-    for line in file_content.lines() {
-        let linelen = line.len() as u16;
-        let row = Erow {
-            size: linelen,
+    // Normalize to LF so the byte offsets derived from `lines()`-built rows in
+    // `cursor_offset` match the bytes the piece table actually holds; otherwise
+    // the stripped `\r` of a CRLF file skews every insert/delete near line ends.
+    let file_content = file_content.replace("\r\n", "\n");
+    editor.trailing_newline = file_content.ends_with('\n');
+    editor.piece_table = PieceTable::new(file_content);
+    rebuild_rows(editor);
+}
+
+// Refresh the materialized `rows` to cover the current viewport (plus a screen
+// of margin) from the caching view. No-op when the file is held in memory.
+fn file_view_materialize(editor: &mut EditorConfig) {
+    let first = editor.row_offset;
+    let last = editor
+        .row_offset
+        .saturating_add(editor.screen_rows.saturating_mul(2));
+    if editor.file_view.is_some() {
+        let (rows, base, total) = editor
+            .file_view
+            .as_mut()
+            .unwrap()
+            .rows_for_range(first, last);
+        editor.rows = rows;
+        editor.row_base = base;
+        editor.num_rows = total;
+    }
+}
+
+// Borrow the `Erow` for an absolute file row, translating through `row_base`.
+fn row_at(editor: &EditorConfig, file_row: u16) -> Option<&Erow> {
+    if file_row < editor.row_base {
+        return None;
+    }
+    editor.rows.get((file_row - editor.row_base) as usize)
+}
+
+// Rebuild the displayed `Erow`s from the current piece-table contents. Called
+// after every edit so rendering always reflects the document.
+fn rebuild_rows(editor: &mut EditorConfig) {
+    editor.rows.clear();
+    for line in editor.piece_table.text().lines() {
+        editor.rows.push(Erow {
+            size: line.len() as u16,
             chars: line.to_string(),
+            render: render_chars(line),
+        });
+    }
+    editor.num_rows = editor.rows.len() as u16;
+}
+
+// Byte offset of the cursor within the logical document.
+fn cursor_offset(editor: &EditorConfig) -> usize {
+    let mut offset = 0;
+    let last = (editor.cursor_y as usize).min(editor.rows.len());
+    for row in &editor.rows[..last] {
+        offset += row.chars.len() + 1; // + newline
+    }
+    if (editor.cursor_y as usize) < editor.rows.len() {
+        // `cursor_x` is a character column; translate it to a byte offset within
+        // the row so the piece table always splits on a codepoint boundary.
+        let row = &editor.rows[editor.cursor_y as usize];
+        offset += row
+            .chars
+            .char_indices()
+            .nth(editor.cursor_x as usize)
+            .map(|(byte, _)| byte)
+            .unwrap_or(row.chars.len());
+    }
+    offset
+}
+
+fn editor_insert_char(editor: &mut EditorConfig, c: char) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "read-only large-file view");
+        return;
+    }
+    let offset = cursor_offset(editor);
+    let mut buf = [0u8; 4];
+    editor.piece_table.insert(offset, c.encode_utf8(&mut buf));
+    rebuild_rows(editor);
+    editor.cursor_x += 1;
+    editor.dirty += 1;
+}
+
+fn editor_insert_newline(editor: &mut EditorConfig) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "read-only large-file view");
+        return;
+    }
+    let offset = cursor_offset(editor);
+    editor.piece_table.insert(offset, "\n");
+    rebuild_rows(editor);
+    editor.cursor_y += 1;
+    editor.cursor_x = 0;
+    editor.dirty += 1;
+}
+
+// Backspace: delete the character before the cursor, merging lines when at the
+// start of a row.
+fn editor_delete_char_back(editor: &mut EditorConfig) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "read-only large-file view");
+        return;
+    }
+    let offset = cursor_offset(editor);
+    if offset == 0 {
+        return;
+    }
+    if editor.cursor_x > 0 {
+        // Remove the whole codepoint before the cursor, not just one byte.
+        let row = &editor.rows[editor.cursor_y as usize];
+        let clen = row
+            .chars
+            .chars()
+            .nth(editor.cursor_x as usize - 1)
+            .map(|c| c.len_utf8())
+            .unwrap_or(1);
+        editor.piece_table.delete(offset - clen, clen);
+        editor.cursor_x -= 1;
+    } else if editor.cursor_y > 0 {
+        // Merge with the previous line; the column lands at its end, in chars.
+        let prev_len = editor.rows[editor.cursor_y as usize - 1].chars.chars().count() as u16;
+        editor.piece_table.delete(offset - 1, 1);
+        editor.cursor_y -= 1;
+        editor.cursor_x = prev_len;
+    }
+    rebuild_rows(editor);
+    editor.dirty += 1;
+}
+
+// Delete the character at the cursor (Delete key).
+fn editor_delete_char(editor: &mut EditorConfig) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "read-only large-file view");
+        return;
+    }
+    let offset = cursor_offset(editor);
+    if offset < editor.piece_table.total_len() {
+        // Remove the whole codepoint at the cursor (the newline at end of line).
+        let clen = editor
+            .rows
+            .get(editor.cursor_y as usize)
+            .and_then(|row| row.chars.chars().nth(editor.cursor_x as usize))
+            .map(|c| c.len_utf8())
+            .unwrap_or(1);
+        editor.piece_table.delete(offset, clen);
+        rebuild_rows(editor);
+        editor.dirty += 1;
+    }
+}
+
+// Join the rows with newlines and write them back to the file. Prompts for a
+// name when the buffer has none, and resets the dirty counter on success.
+fn editor_save(editor: &mut EditorConfig, rx: &Receiver<Event>) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "Can't save: large-file view is read-only");
+        return;
+    }
+    if editor.filename.is_none() {
+        match editor_prompt(editor, rx, "Save as: {} (ESC to cancel)", None) {
+            Some(name) => editor.filename = Some(PathBuf::from(name)),
+            None => {
+                set_status_message(editor, "Save aborted");
+                return;
+            }
+        }
+    }
+
+    let path = editor.filename.clone().unwrap();
+    // Separate rows with newlines, and terminate the last row only if the
+    // original file did, so a newline-terminated file round-trips unchanged and
+    // a file without one does not silently gain a terminator.
+    let mut contents = String::new();
+    for (i, row) in editor.rows.iter().enumerate() {
+        contents.push_str(&row.chars);
+        if i + 1 < editor.rows.len() || editor.trailing_newline {
+            contents.push('\n');
+        }
+    }
+    match fs::write(&path, contents.as_bytes()) {
+        Ok(_) => {
+            editor.dirty = 0;
+            set_status_message(editor, &format!("{} bytes written to disk", contents.len()));
+        }
+        Err(e) => set_status_message(editor, &format!("Can't save! I/O error: {}", e)),
+    }
+}
+
+// Read a line of input on the message bar. `prompt` may contain a `{}`
+// placeholder which is replaced by what the user has typed so far. When
+// `callback` is set it is invoked after every keystroke (and on Enter/Escape)
+// with the current input and the key pressed, driving live features such as
+// incremental search. Returns the entered text on Enter, or `None` on Escape.
+fn editor_prompt(
+    editor: &mut EditorConfig,
+    rx: &Receiver<Event>,
+    prompt: &str,
+    callback: Option<fn(&mut EditorConfig, &str, u32)>,
+) -> Option<String> {
+    let mut buf = String::new();
+    let mut sb = ScreenBuffer::new();
+    loop {
+        set_status_message(editor, &prompt.replace("{}", &buf));
+        editor_refresh_screen(editor, &mut sb);
+        sb.flush();
+
+        let c = match rx.recv() {
+            Ok(Event::Key(k)) => k,
+            Ok(Event::Resize(cols, rows)) => {
+                editor.screen_cols = cols;
+                editor.screen_rows = rows.saturating_sub(2);
+                continue;
+            }
+            Err(_) => return None,
         };
-        editor.rows.push(row);
-        editor.num_rows += 1;
+        match c {
+            x if x == EditorKey::Delete as u32 || x == 127 || x == ctrl_key('h') => {
+                buf.pop();
+            }
+            27 => {
+                set_status_message(editor, "");
+                if let Some(cb) = callback {
+                    cb(editor, &buf, c);
+                }
+                return None;
+            }
+            13 => {
+                if !buf.is_empty() {
+                    set_status_message(editor, "");
+                    if let Some(cb) = callback {
+                        cb(editor, &buf, c);
+                    }
+                    return Some(buf);
+                }
+            }
+            x if !is_cntrl(x) && x < 128 => buf.push(x as u8 as char),
+            _ => (),
+        }
+        if let Some(cb) = callback {
+            cb(editor, &buf, c);
+        }
+    }
+}
+
+// Ctrl-F incremental search: reads a query on the message line, jumping to the
+// first match as the user types. Restores the viewport if the search is
+// cancelled with Escape.
+fn editor_find(editor: &mut EditorConfig, rx: &Receiver<Event>) {
+    if editor.file_view.is_some() {
+        set_status_message(editor, "Search is unavailable in large-file view");
+        return;
+    }
+    let saved_cx = editor.cursor_x;
+    let saved_cy = editor.cursor_y;
+    let saved_col_offset = editor.col_offset;
+    let saved_row_offset = editor.row_offset;
+
+    editor.find_last_match = -1;
+    editor.find_direction = 1;
+
+    let query = editor_prompt(
+        editor,
+        rx,
+        "Search: {} (Use ESC/Arrows/Enter)",
+        Some(editor_find_callback),
+    );
+
+    if query.is_none() {
+        editor.cursor_x = saved_cx;
+        editor.cursor_y = saved_cy;
+        editor.col_offset = saved_col_offset;
+        editor.row_offset = saved_row_offset;
+    }
+    editor.find_hl_row = None;
+}
+
+// Per-keystroke search step. Arrow keys move between matches; any other key
+// restarts the search from the top. Matches are found in each row's rendered
+// text and the render index is mapped back to a `cursor_x`.
+fn editor_find_callback(editor: &mut EditorConfig, query: &str, key: u32) {
+    editor.find_hl_row = None;
+
+    if key == 13 || key == 27 {
+        editor.find_last_match = -1;
+        editor.find_direction = 1;
+        return;
+    } else if key == EditorKey::Right as u32 || key == EditorKey::Down as u32 {
+        editor.find_direction = 1;
+    } else if key == EditorKey::Left as u32 || key == EditorKey::Up as u32 {
+        editor.find_direction = -1;
+    } else {
+        editor.find_last_match = -1;
+        editor.find_direction = 1;
+    }
+
+    if query.is_empty() || editor.num_rows == 0 {
+        return;
+    }
+
+    if editor.find_last_match == -1 {
+        editor.find_direction = 1;
+    }
+
+    let num_rows = editor.num_rows as i32;
+    let mut current = editor.find_last_match;
+    for _ in 0..num_rows {
+        current += editor.find_direction;
+        if current == -1 {
+            current = num_rows - 1;
+        } else if current == num_rows {
+            current = 0;
+        }
+
+        let row = &editor.rows[current as usize];
+        if let Some(byte_idx) = row.render.find(query) {
+            // `find` yields a byte offset; the render layer counts in characters.
+            let rx = row.render[..byte_idx].chars().count();
+            editor.find_last_match = current;
+            editor.cursor_y = current as u16;
+            editor.cursor_x = rx_to_cx(row, rx as u16);
+            // Scroll so the match lands at the top of the screen.
+            editor.row_offset = editor.num_rows;
+            editor.find_hl_row = Some(current as usize);
+            editor.find_hl_start = rx;
+            editor.find_hl_len = query.chars().count();
+            break;
+        }
     }
 }
 
@@ -270,24 +1077,6 @@ fn move_cursor(editor: &mut EditorConfig, key: EditorKey) {
     }
 }
 
-fn clear_and_reset_cursor(sb: Option<&mut ScreenBuffer>) {
-    if let Some(buf) = sb {
-        buf.append(CLEAR_SCREEN);
-        set_cursor_position(Some(buf), 1, 1);
-    } else {
-        print!("{}", CLEAR_SCREEN);
-        set_cursor_position(None, 1, 1);
-    }
-
-    match io::stdout().flush() {
-        Ok(_) => (),
-        Err(e) => die(
-            "flushing stdout after clear and reset cursor",
-            &Some(Box::new(e)),
-        ),
-    }
-}
-
 fn editor_draw_rows(sb: &mut ScreenBuffer, editor: &EditorConfig) {
     for y in 0..editor.screen_rows {
         let file_row = y + editor.row_offset;
@@ -310,47 +1099,132 @@ fn editor_draw_rows(sb: &mut ScreenBuffer, editor: &EditorConfig) {
             } else {
                 sb.append("~");
             }
-        } else {
-            let len = editor.rows[file_row as usize]
-                .size
-                .saturating_sub(editor.col_offset);
-            if len > 0 {
-                sb.append(&editor.rows[file_row as usize].chars[editor.col_offset as usize..]);
-            } else {
-                sb.append("");
+        } else if let Some(row) = row_at(editor, file_row) {
+            // Render columns are counted in characters, so slice by character to
+            // stay on UTF-8 boundaries instead of indexing the string by byte.
+            let render = &row.render;
+            let render_cols = render.chars().count();
+            if (editor.col_offset as usize) < render_cols {
+                let abs_start = editor.col_offset as usize;
+                let visible: String = render
+                    .chars()
+                    .skip(abs_start)
+                    .take(editor.screen_cols as usize)
+                    .collect();
+                let len = visible.chars().count();
+                let abs_end = abs_start + len;
+
+                let hs = editor.find_hl_start;
+                let he = editor.find_hl_start + editor.find_hl_len;
+                if editor.find_hl_row == Some(file_row as usize) && he > abs_start && hs < abs_end {
+                    // Invert-video the matched substring with SGR codes.
+                    let s = hs.max(abs_start) - abs_start;
+                    let e = he.min(abs_end) - abs_start;
+                    let pre: String = visible.chars().take(s).collect();
+                    let mid: String = visible.chars().skip(s).take(e - s).collect();
+                    let post: String = visible.chars().skip(e).collect();
+                    sb.append(&pre);
+                    sb.append("\x1b[7m");
+                    sb.append(&mid);
+                    sb.append("\x1b[m");
+                    sb.append(&post);
+                } else {
+                    sb.append(&visible);
+                }
             }
+        } else {
+            sb.append("~");
         }
         sb.append(CLEAR_LINE);
-        if y < editor.screen_rows - 1 {
-            sb.append("\r\n");
+        sb.append("\r\n");
+    }
+}
+
+// Inverted-video status bar: filename, line count and dirty indicator on the
+// left, current line number on the right.
+fn editor_draw_status_bar(sb: &mut ScreenBuffer, editor: &EditorConfig) {
+    sb.append("\x1b[7m");
+    let name = match &editor.filename {
+        Some(path) => path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("[No Name]"),
+        None => "[No Name]",
+    };
+    let dirty = if editor.dirty > 0 { "(modified)" } else { "" };
+    let left = format!(
+        "-- {} -- {} - {} lines {}",
+        editor.mode.label(),
+        name,
+        editor.num_rows,
+        dirty
+    );
+    let right = format!("{}/{}", editor.cursor_y + 1, editor.num_rows);
+
+    let cols = editor.screen_cols as usize;
+    // Slice by characters, consistent with `editor_draw_rows`, so a non-ASCII
+    // filename on a narrow terminal doesn't split a codepoint and panic.
+    let right_cols = right.chars().count();
+    let mut len = left.chars().count().min(cols);
+    let truncated: String = left.chars().take(len).collect();
+    sb.append(&truncated);
+    while len < cols {
+        if cols - len == right_cols {
+            sb.append(&right);
+            break;
         }
+        sb.append(" ");
+        len += 1;
+    }
+    sb.append("\x1b[m");
+    sb.append("\r\n");
+}
+
+// Transient message line: shown only while the message is fresh (~5 seconds).
+fn editor_draw_message_bar(sb: &mut ScreenBuffer, editor: &EditorConfig) {
+    sb.append(CLEAR_LINE);
+    if editor.status_message_time.elapsed() < Duration::from_secs(5) {
+        let msg: String = editor
+            .status_message
+            .chars()
+            .take(editor.screen_cols as usize)
+            .collect();
+        sb.append(&msg);
     }
 }
 
 fn scroll_screen(editor: &mut EditorConfig) {
+    editor.render_x = match row_at(editor, editor.cursor_y) {
+        Some(row) => cx_to_rx(row, editor.cursor_x),
+        None => 0,
+    };
+
     if editor.cursor_y < editor.row_offset {
         editor.row_offset = editor.cursor_y;
     }
     if editor.cursor_y >= editor.row_offset + editor.screen_rows {
         editor.row_offset = editor.cursor_y - editor.screen_rows + 1;
     }
-    if editor.cursor_x < editor.col_offset {
-        editor.col_offset = editor.cursor_x;
+    if editor.render_x < editor.col_offset {
+        editor.col_offset = editor.render_x;
     }
-    if editor.cursor_x >= editor.col_offset + editor.screen_cols {
-        editor.col_offset = editor.cursor_x - editor.screen_cols + 1;
+    if editor.render_x >= editor.col_offset + editor.screen_cols {
+        editor.col_offset = editor.render_x - editor.screen_cols + 1;
     }
 }
 
 fn editor_refresh_screen(editor: &mut EditorConfig, sb: &mut ScreenBuffer) {
     scroll_screen(editor);
+    file_view_materialize(editor);
     toggle_cursor(sb, false);
     set_cursor_position(Some(sb), 1, 1);
     editor_draw_rows(sb, editor);
+    editor_draw_status_bar(sb, editor);
+    editor_draw_message_bar(sb, editor);
     set_cursor_position(
         Some(sb),
         (editor.cursor_y - editor.row_offset) + 1,
-        (editor.cursor_x - editor.col_offset) + 1,
+        (editor.render_x - editor.col_offset) + 1,
     );
     toggle_cursor(sb, true);
 
@@ -416,53 +1290,201 @@ fn read_key() -> u32 {
 }
 
 // May want to return the character in the future
-fn process_keypress(editor: &mut EditorConfig) {
-    let c = read_key();
+fn process_keypress(editor: &mut EditorConfig, rx: &Receiver<Event>, c: u32) {
+    if c == 0 {
+        return;
+    }
 
-    if is_cntrl(c) && c == ctrl_key('q') {
-        clear_and_reset_cursor(None);
-        disable_raw_mode(&editor.orig_termios);
-        std::process::exit(0);
+    // Ctrl-Q is a global key available in every mode, and is the only one that
+    // may refuse to act (unsaved-changes confirmation).
+    if c == ctrl_key('q') {
+        if editor.dirty > 0 && editor.quit_times > 0 {
+            set_status_message(
+                editor,
+                &format!(
+                    "WARNING!!! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
+                    editor.quit_times
+                ),
+            );
+            editor.quit_times -= 1;
+            return;
+        }
+        editor_quit(editor);
     }
 
-    if c == 0 {
-        return;
+    // Ctrl-S and Ctrl-F are also global.
+    match c {
+        x if x == ctrl_key('s') => editor_save(editor, rx),
+        x if x == ctrl_key('f') => editor_find(editor, rx),
+        _ => match editor.mode {
+            Mode::Normal => process_normal_mode(editor, rx, c),
+            Mode::Insert => process_insert_mode(editor, c),
+            Mode::Command => (),
+        },
     }
 
-    if let Ok(key) = EditorKey::try_from(c) {
-        move_cursor(editor, key);
+    // Any key other than a refused Ctrl-Q resets the quit confirmation counter.
+    editor.quit_times = QUIT_TIMES;
+}
+
+// Normal mode: movement as navigation plus the editing verbs that switch modes.
+fn process_normal_mode(editor: &mut EditorConfig, rx: &Receiver<Event>, c: u32) {
+    match c {
+        x if x == b'i' as u32 => editor.mode = Mode::Insert,
+        x if x == b'a' as u32 => {
+            move_cursor(editor, EditorKey::Right);
+            editor.mode = Mode::Insert;
+        }
+        x if x == b'x' as u32 => editor_delete_char(editor),
+        x if x == b':' as u32 => editor_command_mode(editor, rx),
+        x if x == EditorKey::Delete as u32 => editor_delete_char(editor),
+        _ => {
+            // Arrow keys and h/j/k/l both resolve to movement.
+            if let Ok(key) = EditorKey::try_from(c) {
+                move_cursor(editor, key);
+            }
+        }
+    }
+}
+
+// Insert mode: printable keys type; Escape returns to Normal.
+fn process_insert_mode(editor: &mut EditorConfig, c: u32) {
+    match c {
+        27 => editor.mode = Mode::Normal,
+        13 => editor_insert_newline(editor),
+        x if x == 127 || x == ctrl_key('h') => editor_delete_char_back(editor),
+        x if x == EditorKey::Delete as u32 => editor_delete_char(editor),
+        x if x >= 1000 => {
+            if let Ok(key) = EditorKey::try_from(x) {
+                move_cursor(editor, key);
+            }
+        }
+        x if !is_cntrl(x) && x < 128 => editor_insert_char(editor, x as u8 as char),
+        _ => (),
+    }
+}
+
+// Command mode: read a `:` command on the message line and dispatch it.
+fn editor_command_mode(editor: &mut EditorConfig, rx: &Receiver<Event>) {
+    editor.mode = Mode::Command;
+    let cmd = editor_prompt(editor, rx, ":{}", None);
+    editor.mode = Mode::Normal;
+    if let Some(cmd) = cmd {
+        match cmd.as_str() {
+            "w" => editor_save(editor, rx),
+            "q" => editor_quit(editor),
+            "wq" => {
+                editor_save(editor, rx);
+                editor_quit(editor);
+            }
+            _ => set_status_message(editor, &format!("Unknown command: {}", cmd)),
+        }
     }
+}
 
-    // TODO: create a logging method that puts output at the bottom of the screen
+// Stop the editor after the current key.
+fn editor_quit(editor: &mut EditorConfig) {
+    // Signal the main loop to exit; dropping the guards restores the terminal.
+    editor.should_quit = true;
 }
 
-fn init_editor(orig_termios: Termios) -> EditorConfig {
+fn init_editor() -> EditorConfig {
     let (screen_cols, screen_rows) = get_window_size();
-    EditorConfig {
-        orig_termios,
+    let mut editor = EditorConfig {
         screen_rows,
         screen_cols,
         cursor_x: 0,
         cursor_y: 0,
+        render_x: 0,
         num_rows: 0,
         rows: Vec::new(),
         row_offset: 0,
         col_offset: 0,
-    }
+        piece_table: PieceTable::new(String::new()),
+        dirty: 0,
+        status_message: String::new(),
+        status_message_time: Instant::now(),
+        filename: None,
+        quit_times: QUIT_TIMES,
+        find_last_match: -1,
+        find_direction: 1,
+        find_hl_row: None,
+        find_hl_start: 0,
+        find_hl_len: 0,
+        mode: Mode::Normal,
+        should_quit: false,
+        file_view: None,
+        row_base: 0,
+        trailing_newline: true,
+    };
+    // Reserve two rows at the bottom: the status bar and the message line.
+    editor.screen_rows = editor.screen_rows.saturating_sub(2);
+    editor
+}
+
+// Set the transient message shown on the bottom line. Subsystems such as save
+// and search call this to surface prompts and warnings.
+fn set_status_message(editor: &mut EditorConfig, msg: &str) {
+    editor.status_message = msg.to_string();
+    editor.status_message_time = Instant::now();
 }
 
 fn main() {
-    let orig_termios = setup_terminal();
-    let mut editor = init_editor(orig_termios);
+    // Guards restore the terminal on drop, including during an unwind.
+    let _screen = ScreenGuard::new();
+    let _raw = RawGuard::new();
+    // A panic hook covers panics on the input/signal threads, which never reach
+    // the guards above.
+    install_panic_hook(_raw.orig_termios);
+    let mut editor = init_editor();
     let mut sb = ScreenBuffer::new();
     let args: Vec<String> = env::args().collect();
     if args.len() >= 2 {
         editor_open(&mut editor, &args[1]);
     }
 
+    set_status_message(&mut editor, "HELP: Ctrl-S = save | Ctrl-F = find | Ctrl-Q = quit");
+
+    let (tx, rx) = mpsc::channel();
+
+    // Dedicated thread translating raw stdin bytes into key events.
+    let key_tx = tx.clone();
+    thread::spawn(move || loop {
+        let key = read_key();
+        if key != 0 && key_tx.send(Event::Key(key)).is_err() {
+            break;
+        }
+    });
+
+    // SIGWINCH handler: re-query the window size and push a resize event.
+    let resize_tx = tx;
+    match Signals::new([SIGWINCH]) {
+        Ok(mut signals) => {
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    let (cols, rows) = get_window_size();
+                    if resize_tx.send(Event::Resize(cols, rows)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Err(e) => die("registering SIGWINCH handler", &Some(Box::new(e))),
+    }
+
     loop {
         editor_refresh_screen(&mut editor, &mut sb);
-        process_keypress(&mut editor);
         sb.flush();
+        match rx.recv() {
+            Ok(Event::Key(c)) => process_keypress(&mut editor, &rx, c),
+            Ok(Event::Resize(cols, rows)) => {
+                editor.screen_cols = cols;
+                editor.screen_rows = rows.saturating_sub(2);
+            }
+            Err(e) => die("receiving input event", &Some(Box::new(e))),
+        }
+        if editor.should_quit {
+            break;
+        }
     }
 }